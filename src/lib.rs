@@ -1,9 +1,77 @@
+/// A single vertex attribute selectable in a `VertexLayout`.
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    Uv,
+    Color,
+}
+
+impl VertexAttribute {
+    /// Number of floats this attribute contributes to a vertex.
+    pub fn size(&self) -> usize {
+        match self {
+            VertexAttribute::Position => 3,
+            VertexAttribute::Normal   => 3,
+            VertexAttribute::Color    => 3,
+            VertexAttribute::Uv       => 2,
+        }
+    }
+}
+
+/// Whether `to_buffer` interleaves attributes per vertex or emits one
+/// contiguous block per attribute ( struct-of-arrays ).
+pub enum VertexMode {
+    Interleaved,
+    Separate,
+}
+
+/// Describes which attributes `to_buffer` emits, in what order, and how.
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>,
+    pub mode:       VertexMode,
+}
+
+impl VertexLayout {
+    pub fn new( attributes:Vec<VertexAttribute>, mode:VertexMode ) -> Self {
+        Self { attributes, mode }
+    }
+
+    /// Floats per vertex across all selected attributes.
+    pub fn stride(&self) -> usize {
+        self.attributes.iter().map( |a| a.size() ).sum()
+    }
+}
+
+pub struct GroupOBJ {
+    pub name:      String,
+    pub material:  Option<String>,
+    pub smoothing: u32, // s off maps to 0
+    pub faces:     Vec<(u32, u32, u32)>,
+}
+
+impl GroupOBJ {
+    pub fn new_empty() -> Self {
+        Self {
+            name:      String::new(),
+            material:  None,
+            smoothing: 0,
+            faces:     Vec::new(),
+        }
+    }
+}
+
 pub struct MeshOBJ {
     pub positions: Vec<[f32;3]>,
     pub normals:   Vec<[f32;3]>,
     pub uvs:       Vec<[f32;2]>,
     pub colors:    Vec<[f32;3]>,
     pub faces:     Vec<(u32, u32, u32)>,
+    // faces split into their g groups, preserving submesh / smoothing structure
+    pub groups:    Vec<GroupOBJ>,
+    // name given by the last usemtl directive, if any
+    pub material:      Option<String>,
+    // filenames referenced by mtllib, for the caller to resolve and parse_mtl
+    pub material_libs: Vec<String>,
 }
 
 impl MeshOBJ {
@@ -14,37 +82,349 @@ impl MeshOBJ {
             uvs:       Vec::new(),
             colors:    Vec::new(),
             faces:     Vec::new(),
+            groups:    Vec::new(),
+            material:      None,
+            material_libs: Vec::new(),
         }
     }
 
     pub fn as_opengl_format(&self) -> ( Vec<f32>, Vec<u32> ) {
 
-        let mut vertices:     Vec<f32> = Vec::with_capacity( self.faces.len() );
-        let mut mesh_indeces: Vec<u32> = Vec::with_capacity( self.faces.len() );
+        let mut vertices:     Vec<f32> = Vec::new();
+        let mut mesh_indeces: Vec<u32> = Vec::new();
+        // map a resolved ( position, uv, normal ) triple to its output index so
+        // shared face vertices collapse into a single interleaved vertex
+        let mut seen:std::collections::HashMap<(u32, u32, u32), u32> = std::collections::HashMap::new();
 
-        for( idx, index ) in self.faces.iter().enumerate() {
+        for index in self.faces.iter() {
 
-            let pos_i    = (index.0 - 1) as usize;
-            let uv_i     = (index.1 - 1) as usize;
-            let normal_i = (index.2 - 1) as usize;
+            // reuse an already emitted vertex on a hit
+            if let Some( &out ) = seen.get( index ) {
+                mesh_indeces.push( out );
+                continue;
+            }
 
-            vertices.push( self.positions[pos_i][0].clone() );
-            vertices.push( self.positions[pos_i][1].clone() );
-            vertices.push( self.positions[pos_i][2].clone() );
+            let pos_i = (index.0 - 1) as usize;
+            vertices.push( self.positions[pos_i][0] );
+            vertices.push( self.positions[pos_i][1] );
+            vertices.push( self.positions[pos_i][2] );
 
-            vertices.push( self.normals[normal_i][0].clone() );
-            vertices.push( self.normals[normal_i][1].clone() );
-            vertices.push( self.normals[normal_i][2].clone() );
+            // a 0 sentinel means the attribute was absent in the face token
+            if index.2 == 0 {
+                vertices.push( 0.0 );
+                vertices.push( 0.0 );
+                vertices.push( 0.0 );
+            } else {
+                let normal_i = (index.2 - 1) as usize;
+                vertices.push( self.normals[normal_i][0] );
+                vertices.push( self.normals[normal_i][1] );
+                vertices.push( self.normals[normal_i][2] );
+            }
 
-            vertices.push( self.uvs[uv_i][0].clone() );
-            vertices.push( self.uvs[uv_i][1].clone() );
+            if index.1 == 0 {
+                vertices.push( 0.0 );
+                vertices.push( 0.0 );
+            } else {
+                let uv_i = (index.1 - 1) as usize;
+                vertices.push( self.uvs[uv_i][0] );
+                vertices.push( self.uvs[uv_i][1] );
+            }
 
-            mesh_indeces.push( idx as u32 );
+            let out = seen.len() as u32;
+            seen.insert( *index, out );
+            mesh_indeces.push( out );
 
         }
 
         ( vertices, mesh_indeces )
     }
+
+    /// Emit a deduplicated vertex buffer containing only the attributes named in
+    /// `layout`, in the order given, together with the matching index buffer.
+    /// Honors `layout.mode` for interleaved vs. struct-of-arrays output.
+    pub fn to_buffer(&self, layout:&VertexLayout) -> ( Vec<f32>, Vec<u32> ) {
+
+        // dedup face vertices into unique ( position, uv, normal ) triples
+        let mut uniques:Vec<(u32, u32, u32)> = Vec::new();
+        let mut indices:Vec<u32> = Vec::new();
+        let mut seen:std::collections::HashMap<(u32, u32, u32), u32> = std::collections::HashMap::new();
+
+        for index in self.faces.iter() {
+            let out = match seen.get( index ) {
+                Some( &o ) => o,
+                None => {
+                    let o = uniques.len() as u32;
+                    uniques.push( *index );
+                    seen.insert( *index, o );
+                    o
+                }
+            };
+            indices.push( out );
+        }
+
+        let mut vertices:Vec<f32> = Vec::new();
+        match layout.mode {
+            VertexMode::Interleaved => {
+                for vertex in uniques.iter() {
+                    for attribute in layout.attributes.iter() {
+                        self.push_attribute( &mut vertices, vertex, attribute );
+                    }
+                }
+            }
+            VertexMode::Separate => {
+                for attribute in layout.attributes.iter() {
+                    for vertex in uniques.iter() {
+                        self.push_attribute( &mut vertices, vertex, attribute );
+                    }
+                }
+            }
+        }
+
+        ( vertices, indices )
+    }
+
+    // append one attribute of a resolved face vertex, using the 0 sentinel and
+    // empty colors as all-zero fallbacks so every vertex keeps the layout stride
+    fn push_attribute( &self, out:&mut Vec<f32>, vertex:&(u32, u32, u32), attribute:&VertexAttribute ) {
+        match attribute {
+            VertexAttribute::Position => {
+                let pos_i = (vertex.0 - 1) as usize;
+                out.extend_from_slice( &self.positions[pos_i] );
+            }
+            VertexAttribute::Normal => {
+                if vertex.2 == 0 {
+                    out.extend_from_slice( &[ 0.0, 0.0, 0.0 ] );
+                } else {
+                    out.extend_from_slice( &self.normals[(vertex.2 - 1) as usize] );
+                }
+            }
+            VertexAttribute::Uv => {
+                if vertex.1 == 0 {
+                    out.extend_from_slice( &[ 0.0, 0.0 ] );
+                } else {
+                    out.extend_from_slice( &self.uvs[(vertex.1 - 1) as usize] );
+                }
+            }
+            VertexAttribute::Color => {
+                let pos_i = (vertex.0 - 1) as usize;
+                if self.colors.is_empty() {
+                    out.extend_from_slice( &[ 0.0, 0.0, 0.0 ] );
+                } else {
+                    out.extend_from_slice( &self.colors[pos_i] );
+                }
+            }
+        }
+    }
+}
+
+/// A bounding-volume hierarchy over a mesh's triangles. Interior nodes hold the
+/// union AABB of their children; leaves hold the union AABB and the face indices
+/// ( triangle indices into the flat `faces` list ) they cover.
+pub enum Bvh {
+    Node( Box<Bvh>, Box<Bvh>, ( [f32;3], [f32;3] ) ),
+    Leaf( ( [f32;3], [f32;3] ), Vec<usize> ),
+}
+
+impl Bvh {
+    pub fn aabb(&self) -> ( [f32;3], [f32;3] ) {
+        match self {
+            Bvh::Node( _, _, aabb ) => *aabb,
+            Bvh::Leaf( aabb, _ )    => *aabb,
+        }
+    }
+}
+
+// per-triangle metadata used while building the hierarchy
+struct BvhTri {
+    index:    usize,
+    aabb:     ( [f32;3], [f32;3] ),
+    centroid: [f32;3],
+}
+
+fn aabb_union( a:( [f32;3], [f32;3] ), b:( [f32;3], [f32;3] ) ) -> ( [f32;3], [f32;3] ) {
+    let mut min = a.0;
+    let mut max = a.1;
+    for i in 0..3 {
+        if b.0[i] < min[i] { min[i] = b.0[i]; }
+        if b.1[i] > max[i] { max[i] = b.1[i]; }
+    }
+    ( min, max )
+}
+
+fn build_bvh_node( tris:&mut [BvhTri] ) -> Bvh {
+    let mut bounds = tris[0].aabb;
+    for t in tris.iter().skip(1) { bounds = aabb_union( bounds, t.aabb ); }
+
+    // small sets become leaves directly
+    if tris.len() <= 2 {
+        return Bvh::Leaf( bounds, tris.iter().map( |t| t.index ).collect() );
+    }
+
+    // split along the axis of greatest centroid spread, at the median
+    let mut cmin = tris[0].centroid;
+    let mut cmax = tris[0].centroid;
+    for t in tris.iter().skip(1) {
+        for i in 0..3 {
+            if t.centroid[i] < cmin[i] { cmin[i] = t.centroid[i]; }
+            if t.centroid[i] > cmax[i] { cmax[i] = t.centroid[i]; }
+        }
+    }
+    let mut axis = 0;
+    let mut extent = cmax[0] - cmin[0];
+    for i in 1..3 {
+        let e = cmax[i] - cmin[i];
+        if e > extent { extent = e; axis = i; }
+    }
+
+    tris.sort_by( |a, b|
+        a.centroid[axis].partial_cmp( &b.centroid[axis] ).unwrap_or( core::cmp::Ordering::Equal )
+    );
+
+    let mid = tris.len() / 2;
+    let ( left, right ) = tris.split_at_mut( mid );
+    Bvh::Node(
+        Box::new( build_bvh_node( left ) ),
+        Box::new( build_bvh_node( right ) ),
+        bounds,
+    )
+}
+
+// entry distance of a ray through an AABB, or None when it misses ( slab test )
+fn slab_test( aabb:( [f32;3], [f32;3] ), origin:[f32;3], dir:[f32;3] ) -> Option<f32> {
+    let mut tmin = f32::NEG_INFINITY;
+    let mut tmax = f32::INFINITY;
+    for i in 0..3 {
+        let inv = 1.0 / dir[i];
+        let mut t1 = ( aabb.0[i] - origin[i] ) * inv;
+        let mut t2 = ( aabb.1[i] - origin[i] ) * inv;
+        if t1 > t2 { core::mem::swap( &mut t1, &mut t2 ); }
+        if t1 > tmin { tmin = t1; }
+        if t2 < tmax { tmax = t2; }
+    }
+    if tmax >= tmin.max( 0.0 ) { Some( tmin.max( 0.0 ) ) } else { None }
+}
+
+impl MeshOBJ {
+    /// Axis-aligned bounding box of the whole mesh as ( min, max ) corners.
+    pub fn aabb(&self) -> ( [f32;3], [f32;3] ) {
+        let mut min = [ f32::INFINITY; 3 ];
+        let mut max = [ f32::NEG_INFINITY; 3 ];
+        for p in self.positions.iter() {
+            for i in 0..3 {
+                if p[i] < min[i] { min[i] = p[i]; }
+                if p[i] > max[i] { max[i] = p[i]; }
+            }
+        }
+        ( min, max )
+    }
+
+    // resolve the three positions of triangle `tri` ( index into triangles )
+    fn triangle_positions( &self, tri:usize ) -> [[f32;3];3] {
+        let mut verts = [ [ 0.0; 3 ]; 3 ];
+        for ( k, v ) in verts.iter_mut().enumerate() {
+            let pos_i = (self.faces[tri * 3 + k].0 - 1) as usize;
+            *v = self.positions[pos_i];
+        }
+        verts
+    }
+
+    /// Build a bounding-volume hierarchy over the mesh's triangles.
+    pub fn build_bvh(&self) -> Bvh {
+        let count = self.faces.len() / 3;
+        if count == 0 {
+            return Bvh::Leaf( ( [ 0.0; 3 ], [ 0.0; 3 ] ), Vec::new() );
+        }
+
+        let mut tris:Vec<BvhTri> = Vec::with_capacity( count );
+        for tri in 0..count {
+            let v = self.triangle_positions( tri );
+            let mut min = v[0];
+            let mut max = v[0];
+            for vert in v.iter().skip(1) {
+                for i in 0..3 {
+                    if vert[i] < min[i] { min[i] = vert[i]; }
+                    if vert[i] > max[i] { max[i] = vert[i]; }
+                }
+            }
+            let centroid = [
+                ( v[0][0] + v[1][0] + v[2][0] ) / 3.0,
+                ( v[0][1] + v[1][1] + v[2][1] ) / 3.0,
+                ( v[0][2] + v[1][2] + v[2][2] ) / 3.0,
+            ];
+            tris.push( BvhTri { index: tri, aabb: ( min, max ), centroid } );
+        }
+
+        build_bvh_node( &mut tris )
+    }
+
+    /// Cast a ray against the mesh, returning the nearest ( distance, face index )
+    /// hit or `None`. Walks a freshly built BVH, rejecting nodes with a slab test
+    /// and intersecting leaf triangles with Möller–Trumbore.
+    pub fn raycast(&self, origin:[f32;3], dir:[f32;3] ) -> Option<(f32, usize)> {
+        let bvh = self.build_bvh();
+        let mut best:Option<(f32, usize)> = None;
+        self.raycast_node( &bvh, origin, dir, &mut best );
+        best
+    }
+
+    fn raycast_node( &self, node:&Bvh, origin:[f32;3], dir:[f32;3], best:&mut Option<(f32, usize)> ) {
+        // prune whole subtrees that the ray misses or that lie past the best hit
+        match slab_test( node.aabb(), origin, dir ) {
+            Some( entry ) => {
+                if let Some( ( t, _ ) ) = best { if entry > *t { return; } }
+            }
+            None => return,
+        }
+
+        match node {
+            Bvh::Node( left, right, _ ) => {
+                self.raycast_node( left,  origin, dir, best );
+                self.raycast_node( right, origin, dir, best );
+            }
+            Bvh::Leaf( _, faces ) => {
+                for &tri in faces.iter() {
+                    let v = self.triangle_positions( tri );
+                    if let Some( t ) = moller_trumbore( origin, dir, v ) {
+                        match best {
+                            Some( ( bt, _ ) ) if *bt <= t => {}
+                            _ => *best = Some( ( t, tri ) ),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Möller–Trumbore ray/triangle intersection, returning the hit distance along dir
+fn moller_trumbore( origin:[f32;3], dir:[f32;3], v:[[f32;3];3] ) -> Option<f32> {
+    const EPSILON:f32 = 1e-7;
+
+    let sub = |a:[f32;3], b:[f32;3]| [ a[0]-b[0], a[1]-b[1], a[2]-b[2] ];
+    let cross = |a:[f32;3], b:[f32;3]| [
+        a[1]*b[2] - a[2]*b[1],
+        a[2]*b[0] - a[0]*b[2],
+        a[0]*b[1] - a[1]*b[0],
+    ];
+    let dot = |a:[f32;3], b:[f32;3]| a[0]*b[0] + a[1]*b[1] + a[2]*b[2];
+
+    let edge1 = sub( v[1], v[0] );
+    let edge2 = sub( v[2], v[0] );
+    let h = cross( dir, edge2 );
+    let a = dot( edge1, h );
+    if a > -EPSILON && a < EPSILON { return None; } // ray parallel to triangle
+
+    let f = 1.0 / a;
+    let s = sub( origin, v[0] );
+    let u = f * dot( s, h );
+    if !(0.0..=1.0).contains( &u ) { return None; }
+
+    let q = cross( s, edge1 );
+    let w = f * dot( dir, q );
+    if w < 0.0 || u + w > 1.0 { return None; }
+
+    let t = f * dot( edge2, q );
+    if t > EPSILON { Some( t ) } else { None }
 }
 
 impl core::fmt::Display for MeshOBJ {
@@ -81,7 +461,7 @@ impl core::fmt::Display for MeshOBJ {
             for ( idx, p ) in vec.iter().enumerate() {
                 buffer.push_str( &format!("{:6}/{:6}/{:6} ", p.0, p.1, p.2 ) );
                 if idx % 3 == 0 {
-                    buffer.push_str( &format!( "\nf " ) );
+                    buffer.push_str( "\nf " );
                 }
             }
             buffer
@@ -101,12 +481,134 @@ impl core::fmt::Display for MeshOBJ {
     }
 }
 
+pub struct MaterialOBJ {
+    pub name:     String,
+    pub ambient:  [f32;3], // Ka
+    pub diffuse:  [f32;3], // Kd
+    pub specular: [f32;3], // Ks
+    pub shininess: f32,    // Ns
+    pub opacity:   f32,    // d ( Tr is stored as 1.0 - Tr )
+    pub illum:     u32,
+    pub map_diffuse:  String, // map_Kd
+    pub map_specular: String, // map_Ks
+    pub map_bump:     String, // map_Bump
+}
+
+impl MaterialOBJ {
+    pub fn new( name:String ) -> Self {
+        Self {
+            name,
+            ambient:  [ 0.0, 0.0, 0.0 ],
+            diffuse:  [ 1.0, 1.0, 1.0 ],
+            specular: [ 0.0, 0.0, 0.0 ],
+            shininess: 0.0,
+            opacity:   1.0,
+            illum:     0,
+            map_diffuse:  String::new(),
+            map_specular: String::new(),
+            map_bump:     String::new(),
+        }
+    }
+}
+
+pub fn parse_mtl( src:String ) -> Result<Vec<MaterialOBJ>, Error> {
+
+    let mut materials:Vec<MaterialOBJ> = Vec::new();
+
+    let parse_floats = | symbols:&[&str] | -> Result<Vec<f32>, Error> {
+        let mut result = Vec::new();
+        for symbol in symbols.iter().skip(1) {
+            let data = match symbol.parse::<f32>() {
+                Ok(f) => f,
+                Err(e) => return Err(
+                    Error::ParseFloat( format!("Parse Float Error: {}", e) )
+                ),
+            };
+            result.push( data );
+        }
+        Ok( result )
+    };
+
+    for line in src.split( '\n' ) {
+        let line = line.trim();
+        if line.is_empty() { continue; }
+        if line.starts_with( COMMENT ) { continue; }
+
+        let symbols:Vec<&str> = line.split_whitespace().collect();
+
+        match symbols[0] {
+            NEWMTL => {
+                let name = if symbols.len() > 1 { symbols[1].to_owned() } else { String::new() };
+                materials.push( MaterialOBJ::new( name ) );
+            }
+            _ => {
+                // every other directive mutates the material currently being built
+                let material = match materials.last_mut() {
+                    Some(m) => m,
+                    None    => continue,
+                };
+                match symbols[0] {
+                    MTL_KA => {
+                        let c = parse_floats( &symbols )?;
+                        if c.len() < 3 { return Err( Error::MaterialFormat ); }
+                        material.ambient = [ c[0], c[1], c[2] ];
+                    }
+                    MTL_KD => {
+                        let c = parse_floats( &symbols )?;
+                        if c.len() < 3 { return Err( Error::MaterialFormat ); }
+                        material.diffuse = [ c[0], c[1], c[2] ];
+                    }
+                    MTL_KS => {
+                        let c = parse_floats( &symbols )?;
+                        if c.len() < 3 { return Err( Error::MaterialFormat ); }
+                        material.specular = [ c[0], c[1], c[2] ];
+                    }
+                    MTL_NS => {
+                        let c = parse_floats( &symbols )?;
+                        if c.is_empty() { return Err( Error::MaterialFormat ); }
+                        material.shininess = c[0];
+                    }
+                    MTL_D => {
+                        let c = parse_floats( &symbols )?;
+                        if c.is_empty() { return Err( Error::MaterialFormat ); }
+                        material.opacity = c[0];
+                    }
+                    MTL_TR => {
+                        let c = parse_floats( &symbols )?;
+                        if c.is_empty() { return Err( Error::MaterialFormat ); }
+                        material.opacity = 1.0 - c[0];
+                    }
+                    MTL_ILLUM => {
+                        if symbols.len() > 1 {
+                            material.illum = match symbols[1].parse::<u32>() {
+                                Ok(u)  => u,
+                                Err(e) => return Err(
+                                    Error::ParseInt( format!( "Parse Int Error: {}", e ) )
+                                ),
+                            };
+                        }
+                    }
+                    MTL_MAP_KD   => if symbols.len() > 1 { material.map_diffuse  = symbols[1].to_owned(); },
+                    MTL_MAP_KS   => if symbols.len() > 1 { material.map_specular = symbols[1].to_owned(); },
+                    MTL_MAP_BUMP => if symbols.len() > 1 { material.map_bump     = symbols[1].to_owned(); },
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    Ok( materials )
+}
+
 pub fn parse_obj( src:String ) -> Result<Vec<MeshOBJ>, Error> {
 
     let parser =
     | lines:Vec<&str>, index_offset:( u32, u32, u32 ) | -> Result<( MeshOBJ, ( u32, u32, u32 ) ), Error> {
         let mut mesh = MeshOBJ::new_empty();
-        let mut next_index_offset = ( 0u32, 0u32, 0u32 );
+        // the group currently receiving faces; g / usemtl / s mutate or replace it
+        let mut current_group = GroupOBJ::new_empty();
+        let mut active_material:Option<String> = None;
+        let mut active_smoothing:u32 = 0;
         // parse data out of text
         // iterate through each line
         for line in lines.iter() {
@@ -175,42 +677,116 @@ pub fn parse_obj( src:String ) -> Result<Vec<MeshOBJ>, Error> {
                     }
                 }
                 INDEX    => {
+                    // resolve a single index field against the running element count,
+                    // handling spec negative indices ( -k == count - k + 1 ) and the
+                    // empty field ( e.g. the middle of v//vn ) which maps to the 0 sentinel.
+                    // also reports whether the field was a positive ( global ) token, since
+                    // only those need index_offset applied -- negative tokens already resolve
+                    // to an object-local index.
+                    let resolve = | raw:&str, count:usize | -> Result<( u32, bool ), Error> {
+                        if raw.is_empty() { return Ok( ( 0, false ) ); }
+                        let value = match raw.parse::<i64>() {
+                            Ok(i) => i,
+                            Err(e) => return Err(
+                                Error::ParseInt( format!( "Parse Int Error: {}", e ) )
+                            ),
+                        };
+                        if value < 0 {
+                            Ok( ( ( count as i64 + value + 1 ) as u32, false ) )
+                        } else {
+                            Ok( ( value as u32, true ) )
+                        }
+                    };
+
+                    let apply_offset = | value:u32, is_global:bool, offset:u32 | -> u32 {
+                        if value == 0 || !is_global { value } else { value - offset }
+                    };
+
+                    // collect every vertex of the ( possibly n-gon ) face first,
+                    // then fan triangulate below
+                    let mut face_vertices:Vec<(u32, u32, u32)> = Vec::new();
                     for symbol in symbols.iter().skip(1) {
-                        let mut result:Vec<u32> = Vec::new();
                         let sub_symbols:Vec<&str> = symbol.split('/').collect();
-    
-                        for sub_symbol in sub_symbols {
-                            let data = match sub_symbol.parse::<u32>() {
-                                Ok(u) => u,
-                                Err(e) => return Err(
-                                    Error::ParseInt( format!( "Parse Int Error: {}", e ) )
-                                ),
-                            };
-                            result.push( data );
-                        }
-    
-                        match result.len() {
-                            3 => {
-                                if result[0] > next_index_offset.0 { next_index_offset.0 = result[0] }
-                                if result[1] > next_index_offset.1 { next_index_offset.1 = result[1] }
-                                if result[2] > next_index_offset.2 { next_index_offset.2 = result[2] }
-                                mesh.faces.push(
-                                    (
-                                        result[0] - index_offset.0,
-                                        result[1] - index_offset.1,
-                                        result[2] - index_offset.2
-                                    )
-                                );
-                            },
+
+                        // distinguish v, v/vt, v//vn and v/vt/vn layouts
+                        let ( pos_raw, uv_raw, normal_raw ) = match sub_symbols.len() {
+                            1 => ( sub_symbols[0], "",             ""             ),
+                            2 => ( sub_symbols[0], sub_symbols[1], ""             ),
+                            3 => ( sub_symbols[0], sub_symbols[1], sub_symbols[2] ),
                             _ => return Err( Error::FaceIndexFormat ),
+                        };
+
+                        let ( pos,    pos_is_global    ) = resolve( pos_raw,    mesh.positions.len() )?;
+                        let ( uv,     uv_is_global     ) = resolve( uv_raw,     mesh.uvs.len()       )?;
+                        let ( normal, normal_is_global ) = resolve( normal_raw, mesh.normals.len()   )?;
+
+                        face_vertices.push(
+                            (
+                                apply_offset( pos,    pos_is_global,    index_offset.0 ),
+                                apply_offset( uv,     uv_is_global,     index_offset.1 ),
+                                apply_offset( normal, normal_is_global, index_offset.2 ),
+                            )
+                        );
+                    }
+
+                    if face_vertices.len() < 3 { return Err( Error::FaceIndexFormat ); }
+
+                    // simple fan: ( v0, v1, v2 ), ( v0, v2, v3 ) ...
+                    for i in 1..face_vertices.len() - 1 {
+                        for v in [ face_vertices[0], face_vertices[i], face_vertices[i + 1] ] {
+                            mesh.faces.push( v );
+                            current_group.faces.push( v );
                         }
-    
                     }
                 }
+                USEMTL   => {
+                    let name = if symbols.len() > 1 { Some( symbols[1].to_owned() ) } else { None };
+                    mesh.material        = name.clone();
+                    active_material      = name.clone();
+                    current_group.material = name;
+                }
+                MTLLIB   => {
+                    for symbol in symbols.iter().skip(1) {
+                        mesh.material_libs.push( (*symbol).to_owned() );
+                    }
+                }
+                GROUP    => {
+                    // finalize the previous group before starting the named one,
+                    // carrying over the active material / smoothing state
+                    if !current_group.faces.is_empty() || !current_group.name.is_empty() {
+                        mesh.groups.push( current_group );
+                    }
+                    current_group = GroupOBJ::new_empty();
+                    if symbols.len() > 1 { current_group.name = symbols[1].to_owned(); }
+                    current_group.material  = active_material.clone();
+                    current_group.smoothing = active_smoothing;
+                }
+                SMOOTH   => {
+                    active_smoothing = if symbols.len() > 1 && symbols[1] != "off" {
+                        symbols[1].parse::<u32>().unwrap_or( 0 )
+                    } else {
+                        0
+                    };
+                    current_group.smoothing = active_smoothing;
+                }
                 _ => { continue; }
             };
     
         }
+        // flush the last in-progress group
+        if !current_group.faces.is_empty() || !current_group.name.is_empty() {
+            mesh.groups.push( current_group );
+        }
+        // v/vt/vn indeces are numbered globally across the whole file, so the
+        // offset the next object needs is simply this object's incoming offset
+        // plus however many of each it declared -- independent of which ones its
+        // faces actually referenced ( a face-index heuristic can't see elements
+        // an object never referenced, e.g. one that only uses negative indeces )
+        let next_index_offset = (
+            index_offset.0 + mesh.positions.len() as u32,
+            index_offset.1 + mesh.uvs.len()       as u32,
+            index_offset.2 + mesh.normals.len()   as u32,
+        );
         Ok(( mesh, next_index_offset ))
     };
 
@@ -232,6 +808,214 @@ pub fn parse_obj( src:String ) -> Result<Vec<MeshOBJ>, Error> {
     
 }
 
+/// Serialize one or more parsed meshes into the Inter-Quake Model ( IQM )
+/// binary format ( version 2 ). Vertices are deduplicated per mesh and the
+/// attributes present across the input ( position is always emitted; texcoord,
+/// normal and color only when some mesh carries them ) are written as parallel
+/// vertex arrays. Since OBJ has no skeleton the joints / poses / anims sections
+/// are left empty. The returned buffer is self contained and its embedded
+/// filesize equals its length.
+pub fn export_iqm( meshes:&[MeshOBJ] ) -> Vec<u8> {
+    use std::collections::HashMap;
+
+    // IQM vertex-array attribute types and the FLOAT format
+    const IQM_POSITION:u32 = 0;
+    const IQM_TEXCOORD:u32 = 1;
+    const IQM_NORMAL:u32   = 2;
+    const IQM_COLOR:u32    = 6;
+    const IQM_FLOAT:u32    = 7;
+    const HEADER_SIZE:u32  = 124;
+
+    // intern a name into the text blob, returning its byte offset ( 0 == empty )
+    fn intern( text:&mut Vec<u8>, table:&mut HashMap<String, u32>, name:&str ) -> u32 {
+        if name.is_empty() { return 0; }
+        if let Some( &ofs ) = table.get( name ) { return ofs; }
+        let ofs = text.len() as u32;
+        text.extend_from_slice( name.as_bytes() );
+        text.push( 0 );
+        table.insert( name.to_owned(), ofs );
+        ofs
+    }
+
+    let have_uv     = meshes.iter().any( |m| !m.uvs.is_empty()     );
+    let have_normal = meshes.iter().any( |m| !m.normals.is_empty() );
+    let have_color  = meshes.iter().any( |m| !m.colors.is_empty()  );
+
+    // parallel ( struct-of-arrays ) vertex data, shared across every mesh
+    let mut positions:Vec<[f32;3]> = Vec::new();
+    let mut uvs:Vec<[f32;2]>       = Vec::new();
+    let mut normals:Vec<[f32;3]>   = Vec::new();
+    let mut colors:Vec<[f32;3]>    = Vec::new();
+    let mut triangles:Vec<[u32;3]> = Vec::new();
+
+    // text blob starts with a \0 so offset 0 reads as the empty string
+    let mut text:Vec<u8> = vec![ 0 ];
+    let mut text_table:HashMap<String, u32> = HashMap::new();
+    // ( name_ofs, material_ofs, first_vertex, num_vertexes, first_triangle, num_triangles )
+    let mut mesh_records:Vec<(u32, u32, u32, u32, u32, u32)> = Vec::new();
+
+    for mesh in meshes.iter() {
+        let first_vertex   = positions.len() as u32;
+        let first_triangle = triangles.len() as u32;
+
+        let mut seen:HashMap<(u32, u32, u32), u32> = HashMap::new();
+        let mut local_indices:Vec<u32> = Vec::new();
+
+        for face in mesh.faces.iter() {
+            let local = match seen.get( face ) {
+                Some( &l ) => l,
+                None => {
+                    let l = positions.len() as u32 - first_vertex;
+
+                    let pos_i = (face.0 - 1) as usize;
+                    positions.push( mesh.positions[pos_i] );
+
+                    if have_uv {
+                        uvs.push( if face.1 == 0 { [ 0.0, 0.0 ] } else { mesh.uvs[(face.1 - 1) as usize] } );
+                    }
+                    if have_normal {
+                        normals.push( if face.2 == 0 { [ 0.0, 0.0, 0.0 ] } else { mesh.normals[(face.2 - 1) as usize] } );
+                    }
+                    if have_color {
+                        colors.push( if mesh.colors.is_empty() { [ 0.0, 0.0, 0.0 ] } else { mesh.colors[pos_i] } );
+                    }
+
+                    seen.insert( *face, l );
+                    l
+                }
+            };
+            local_indices.push( local );
+        }
+
+        for tri in local_indices.chunks( 3 ) {
+            if tri.len() < 3 { break; }
+            triangles.push( [ first_vertex + tri[0], first_vertex + tri[1], first_vertex + tri[2] ] );
+        }
+
+        let name_ofs = 0; // OBJ object names are not retained by parse_obj
+        let material = mesh.material.clone().unwrap_or_default();
+        let material_ofs = intern( &mut text, &mut text_table, &material );
+
+        mesh_records.push((
+            name_ofs,
+            material_ofs,
+            first_vertex,
+            positions.len()  as u32 - first_vertex,
+            first_triangle,
+            triangles.len()  as u32 - first_triangle,
+        ));
+    }
+
+    // build the vertex-array descriptor list in the canonical order
+    let mut arrays:Vec<(u32, u32, Vec<u8>)> = Vec::new(); // ( type, size, data )
+    let floats3 = |v:&[[f32;3]]| -> Vec<u8> {
+        let mut b = Vec::with_capacity( v.len() * 12 );
+        for p in v { for f in p { b.extend_from_slice( &f.to_le_bytes() ); } }
+        b
+    };
+    let floats2 = |v:&[[f32;2]]| -> Vec<u8> {
+        let mut b = Vec::with_capacity( v.len() * 8 );
+        for p in v { for f in p { b.extend_from_slice( &f.to_le_bytes() ); } }
+        b
+    };
+    arrays.push(( IQM_POSITION, 3, floats3( &positions ) ));
+    if have_uv     { arrays.push(( IQM_TEXCOORD, 2, floats2( &uvs )     )); }
+    if have_normal { arrays.push(( IQM_NORMAL,   3, floats3( &normals ) )); }
+    if have_color  { arrays.push(( IQM_COLOR,    3, floats3( &colors )  )); }
+
+    // text is padded so following sections stay 4-byte aligned
+    while !text.len().is_multiple_of( 4 ) { text.push( 0 ); }
+
+    let num_vertexarrays = arrays.len() as u32;
+    let num_vertexes     = positions.len() as u32;
+    let num_triangles    = triangles.len() as u32;
+    let num_meshes       = mesh_records.len() as u32;
+
+    let ofs_text         = HEADER_SIZE;
+    let ofs_vertexarrays = ofs_text + text.len() as u32;
+    let ofs_vertexes     = ofs_vertexarrays + num_vertexarrays * 20;
+
+    // resolve each array's absolute data offset, laid out back to back
+    let mut array_offsets:Vec<u32> = Vec::with_capacity( arrays.len() );
+    let mut cursor = ofs_vertexes;
+    for ( _, _, data ) in arrays.iter() {
+        array_offsets.push( cursor );
+        cursor += data.len() as u32;
+    }
+    let ofs_triangles = cursor;
+    let ofs_meshes    = ofs_triangles + num_triangles * 12;
+    let filesize      = ofs_meshes + num_meshes * 24;
+
+    let mut out:Vec<u8> = Vec::with_capacity( filesize as usize );
+    let push_u32 = |out:&mut Vec<u8>, v:u32| out.extend_from_slice( &v.to_le_bytes() );
+
+    // header
+    out.extend_from_slice( b"INTERQUAKEMODEL\0" );
+    push_u32( &mut out, 2 );                 // version
+    push_u32( &mut out, filesize );
+    push_u32( &mut out, 0 );                 // flags
+    push_u32( &mut out, text.len() as u32 ); // num_text
+    push_u32( &mut out, ofs_text );
+    push_u32( &mut out, num_meshes );
+    push_u32( &mut out, ofs_meshes );
+    push_u32( &mut out, num_vertexarrays );
+    push_u32( &mut out, num_vertexes );
+    push_u32( &mut out, ofs_vertexarrays );
+    push_u32( &mut out, num_triangles );
+    push_u32( &mut out, ofs_triangles );
+    push_u32( &mut out, 0 );                 // ofs_adjacency
+    push_u32( &mut out, 0 );                 // num_joints
+    push_u32( &mut out, 0 );                 // ofs_joints
+    push_u32( &mut out, 0 );                 // num_poses
+    push_u32( &mut out, 0 );                 // ofs_poses
+    push_u32( &mut out, 0 );                 // num_anims
+    push_u32( &mut out, 0 );                 // ofs_anims
+    push_u32( &mut out, 0 );                 // num_frames
+    push_u32( &mut out, 0 );                 // num_framechannels
+    push_u32( &mut out, 0 );                 // ofs_frames
+    push_u32( &mut out, 0 );                 // ofs_bounds
+    push_u32( &mut out, 0 );                 // num_comment
+    push_u32( &mut out, 0 );                 // ofs_comment
+    push_u32( &mut out, 0 );                 // num_extensions
+    push_u32( &mut out, 0 );                 // ofs_extensions
+
+    // text blob
+    out.extend_from_slice( &text );
+
+    // vertex-array descriptors
+    for ( i, ( ty, size, _ ) ) in arrays.iter().enumerate() {
+        push_u32( &mut out, *ty );             // type
+        push_u32( &mut out, 0 );               // flags
+        push_u32( &mut out, IQM_FLOAT );       // format
+        push_u32( &mut out, *size );           // size
+        push_u32( &mut out, array_offsets[i] ); // offset
+    }
+
+    // vertex data
+    for ( _, _, data ) in arrays.iter() {
+        out.extend_from_slice( data );
+    }
+
+    // triangles
+    for tri in triangles.iter() {
+        push_u32( &mut out, tri[0] );
+        push_u32( &mut out, tri[1] );
+        push_u32( &mut out, tri[2] );
+    }
+
+    // mesh records
+    for ( name, material, first_vertex, num_vertexes, first_triangle, num_triangles ) in mesh_records.iter() {
+        push_u32( &mut out, *name );
+        push_u32( &mut out, *material );
+        push_u32( &mut out, *first_vertex );
+        push_u32( &mut out, *num_vertexes );
+        push_u32( &mut out, *first_triangle );
+        push_u32( &mut out, *num_triangles );
+    }
+
+    out
+}
+
 #[derive(Debug)]
 pub enum Error {
     ParseFloat(String),
@@ -240,6 +1024,7 @@ pub enum Error {
     UVFormat,
     NormalFormat,
     FaceIndexFormat,
+    MaterialFormat,
 }
 
 impl Error {
@@ -248,14 +1033,16 @@ impl Error {
             Error::ParseFloat(s) => s.clone(),
             Error::ParseInt(s)   => s.clone(),
             Error::PositionColorFormat    =>
-                format!("Formatting Error: Positions/Vertex Colors are not properly formatted!"),
+                "Formatting Error: Positions/Vertex Colors are not properly formatted!".to_owned(),
             Error::UVFormat =>
-                format!("Formatting Error: UVs are not properly formatted!"),
-            Error::NormalFormat => 
-                format!("Formatting Error: Normals are not properly formatted!"),
-            Error::FaceIndexFormat => 
-                format!("Formatting Error: Face Indeces are not properly formatted!"),
-                
+                "Formatting Error: UVs are not properly formatted!".to_owned(),
+            Error::NormalFormat =>
+                "Formatting Error: Normals are not properly formatted!".to_owned(),
+            Error::FaceIndexFormat =>
+                "Formatting Error: Face Indeces are not properly formatted!".to_owned(),
+            Error::MaterialFormat =>
+                "Formatting Error: Material is not properly formatted!".to_owned(),
+
         }
     }
 }
@@ -271,3 +1058,76 @@ const POSITION:&str = "v";
 const UV:&str       = "vt";
 const NORMAL:&str   = "vn";
 const INDEX:&str    = "f";
+const USEMTL:&str   = "usemtl";
+const MTLLIB:&str   = "mtllib";
+const GROUP:&str    = "g";
+const SMOOTH:&str   = "s";
+
+const NEWMTL:&str       = "newmtl";
+const MTL_KA:&str       = "Ka";
+const MTL_KD:&str       = "Kd";
+const MTL_KS:&str       = "Ks";
+const MTL_NS:&str       = "Ns";
+const MTL_D:&str        = "d";
+const MTL_TR:&str       = "Tr";
+const MTL_ILLUM:&str    = "illum";
+const MTL_MAP_KD:&str   = "map_Kd";
+const MTL_MAP_KS:&str   = "map_Ks";
+const MTL_MAP_BUMP:&str = "map_Bump";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_indices_in_second_object_do_not_underflow() {
+        let src = concat!(
+            "o First\n",
+            "v 0.0 0.0 0.0\n",
+            "v 1.0 0.0 0.0\n",
+            "v 0.0 1.0 0.0\n",
+            "f 1 2 3\n",
+            "o Second\n",
+            "v 0.0 0.0 1.0\n",
+            "v 1.0 0.0 1.0\n",
+            "v 0.0 1.0 1.0\n",
+            "f -3 -2 -1\n",
+        ).to_string();
+
+        let meshes = parse_obj( src ).expect( "multi-object file with negative indices should parse" );
+
+        assert_eq!( meshes.len(), 2 );
+        // negative indices are object-local, so the second object's face must
+        // index into its own positions ( 1-based ), not the global stream
+        assert_eq!( meshes[1].faces, vec![ ( 1, 0, 0 ), ( 2, 0, 0 ), ( 3, 0, 0 ) ] );
+    }
+
+    #[test]
+    fn offset_tracks_vertex_counts_not_just_referenced_indices() {
+        // object B only ever references its own vertices via negative indeces,
+        // so a later object ( C ) using positive / global indeces must still get
+        // an offset that accounts for every vertex B declared
+        let src = concat!(
+            "o A\n",
+            "v 0.0 0.0 0.0\n",
+            "v 1.0 0.0 0.0\n",
+            "v 0.0 1.0 0.0\n",
+            "f 1 2 3\n",
+            "o B\n",
+            "v 0.0 0.0 1.0\n",
+            "v 1.0 0.0 1.0\n",
+            "v 0.0 1.0 1.0\n",
+            "f -3 -2 -1\n",
+            "o C\n",
+            "v 0.0 0.0 2.0\n",
+            "v 1.0 0.0 2.0\n",
+            "v 0.0 1.0 2.0\n",
+            "f 7 8 9\n",
+        ).to_string();
+
+        let meshes = parse_obj( src ).expect( "three-object file should parse" );
+
+        assert_eq!( meshes.len(), 3 );
+        assert_eq!( meshes[2].faces, vec![ ( 1, 0, 0 ), ( 2, 0, 0 ), ( 3, 0, 0 ) ] );
+    }
+}